@@ -1,11 +1,17 @@
 use num::{Bounded, Integer, One, Saturating};
-use std::{mem, ops::RangeInclusive};
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, RangeBounds, RangeInclusive},
+};
 
+/// A set of disjoint, coalesced integer ranges backed by a `BTreeMap`.
+///
+/// Ranges are stored keyed by their start, with the value holding the
+/// matching end, so lookups, insertion and in-order traversal are all
+/// `O(log N)` / `O(N)` respectively and never recurse.
 #[derive(Debug, Clone)]
 pub struct RangeTree<T> {
-    range: RangeInclusive<T>,
-    less: Option<Box<RangeTree<T>>>,
-    more: Option<Box<RangeTree<T>>>,
+    ranges: BTreeMap<T, T>,
 }
 
 impl<A: Copy + Clone + Integer + Saturating + One + Bounded> FromIterator<A> for RangeTree<A> {
@@ -25,87 +31,315 @@ impl<A: Copy + Clone + Integer + Saturating + One + Bounded> FromIterator<A> for
 
 impl<T: Copy + Clone + Integer + Saturating + One + Bounded> RangeTree<T> {
     pub fn new(value: T) -> Self {
-        RangeTree {
-            range: value..=value,
-            less: None,
-            more: None,
-        }
+        let mut ranges = BTreeMap::new();
+        ranges.insert(value, value);
+        RangeTree { ranges }
     }
 
     pub fn insert(&mut self, value: T) {
-        if self.range.contains(&value) {
+        let mut start = value;
+        let mut end = value;
+        let mut drop_start = None;
+        let mut drop_end = None;
+
+        if let Some((&pred_start, &pred_end)) = self.ranges.range(..=value).next_back() {
+            if pred_end >= value {
+                // `value` already lies within an existing range.
+                return;
+            }
+            if pred_end.saturating_add(T::one()) == value {
+                start = pred_start;
+                drop_start = Some(pred_start);
+            }
+        }
+
+        if let Some((&succ_start, &succ_end)) = self.ranges.range(value..).next() {
+            if value.saturating_add(T::one()) == succ_start {
+                end = succ_end;
+                drop_end = Some(succ_start);
+            }
+        }
+
+        if let Some(key) = drop_start {
+            self.ranges.remove(&key);
+        }
+        if let Some(key) = drop_end {
+            self.ranges.remove(&key);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn to_vec(&self) -> Vec<RangeInclusive<T>> {
+        self.ranges.iter().map(|(&s, &e)| s..=e).collect()
+    }
+
+    /// Finds the gaps in `range` that aren't covered by any stored range.
+    /// `range` may be any `RangeBounds`, including half-open and unbounded
+    /// ones: `Unbounded` resolves to `T::min_value()`/`T::max_value()`, and
+    /// `Excluded` endpoints are nudged inward by one.
+    pub fn missed_in_range<R: RangeBounds<T>>(&self, range: R) -> Vec<RangeInclusive<T>> {
+        let start = match range.start_bound() {
+            Bound::Included(&v) => v,
+            Bound::Excluded(&v) if v == T::max_value() => return Vec::new(),
+            Bound::Excluded(&v) => v.saturating_add(T::one()),
+            Bound::Unbounded => T::min_value(),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&v) => v,
+            Bound::Excluded(&v) if v == T::min_value() => return Vec::new(),
+            Bound::Excluded(&v) => v.saturating_sub(T::one()),
+            Bound::Unbounded => T::max_value(),
+        };
+
+        let filled = self.to_vec();
+        if filled.is_empty() {
+            return if start <= end { vec![start..=end] } else { Vec::new() };
+        }
+        let head = filled
+            .first()
+            .and_then(|c| (c.start() > &start).then_some(start..=c.start().saturating_sub(T::one())));
+        let tail = filled
+            .last()
+            .and_then(|c| (c.end() < &end).then_some(c.end().saturating_add(T::one())..=end));
+        let missed = head
+            .into_iter()
+            .chain(filled.windows(2).map(|w| {
+                w[0].end().saturating_add(T::one()).max(start)
+                    ..=w[1].start().saturating_sub(T::one()).min(end)
+            }))
+            .chain(tail)
+            .filter(|m| m.end() >= &start && m.start() <= &end)
+            .collect::<Vec<RangeInclusive<T>>>();
+
+        missed
+    }
+
+    /// Inserts a whole range, coalescing it with any existing ranges it
+    /// overlaps or touches (i.e. `a.end() + 1 == b.start()`).
+    pub fn insert_range(&mut self, r: RangeInclusive<T>) {
+        if r.is_empty() {
             return;
         }
-        if self.range.start() > &value {
-            if self.less.is_none() {
-                self.less = Some(Box::new(RangeTree::new(value)));
+        let mut new_start = *r.start();
+        let mut new_end = *r.end();
+
+        // Bound the scan to the predecessor of `new_start` (the only stored
+        // range that can touch/overlap from below, since stored ranges are
+        // already disjoint and coalesced) through `merge_until`, instead of
+        // scanning from the very start of the map.
+        let scan_start = self
+            .ranges
+            .range(..=new_start)
+            .next_back()
+            .map_or(new_start, |(&start, _)| start);
+        let merge_until = new_end.saturating_add(T::one());
+        let overlapping: Vec<(T, T)> = self
+            .ranges
+            .range(scan_start..=merge_until)
+            .filter(|&(_, &end)| end.saturating_add(T::one()) >= new_start)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+
+        for (start, end) in overlapping {
+            new_start = new_start.min(start);
+            new_end = new_end.max(end);
+            self.ranges.remove(&start);
+        }
+
+        self.ranges.insert(new_start, new_end);
+    }
+
+    /// Builds a `RangeTree` by coalescing an iterator of ranges, merging
+    /// overlapping and adjacent intervals as it goes.
+    pub fn from_ranges<I: IntoIterator<Item = RangeInclusive<T>>>(ranges: I) -> Self {
+        let mut tree = RangeTree {
+            ranges: BTreeMap::new(),
+        };
+        for r in ranges {
+            tree.insert_range(r);
+        }
+        tree
+    }
+
+    /// Removes a single value, splitting its containing range if `value` is
+    /// interior, shrinking it if `value` is an endpoint, or dropping it
+    /// entirely if the range was just that one value.
+    pub fn remove(&mut self, value: T) {
+        if let Some((&start, &end)) = self.ranges.range(..=value).next_back() {
+            if end < value {
+                return;
             }
-            if let Some(less) = &mut self.less {
-                less.insert(value);
-                if less.range.end().saturating_add(T::one()) == *self.range.start() {
-                    self.range = *less.range.start()..=*self.range.end();
-                    self.less = mem::take(&mut less.less);
-                }
+            self.ranges.remove(&start);
+            if start < value {
+                self.ranges.insert(start, value.saturating_sub(T::one()));
             }
-        } else if self.range.end() < &value {
-            if self.more.is_none() {
-                self.more = Some(Box::new(RangeTree::new(value)));
+            if end > value {
+                self.ranges.insert(value.saturating_add(T::one()), end);
             }
-            if let Some(more) = &mut self.more {
-                more.insert(value);
-                if self.range.end().saturating_add(T::one()) == *more.range.start() {
-                    self.range = *self.range.start()..=*more.range.end();
-                    self.more = mem::take(&mut more.more);
-                }
+        }
+    }
+
+    /// Removes a whole range, trimming or splitting every stored range it
+    /// straddles and dropping the ones it fully covers.
+    pub fn remove_range(&mut self, r: RangeInclusive<T>) {
+        if r.is_empty() {
+            return;
+        }
+        let (lo, hi) = (*r.start(), *r.end());
+        // Same bounded-scan trick as `insert_range`: the predecessor of `lo`
+        // is the only stored range that can straddle it from below.
+        let scan_start = self
+            .ranges
+            .range(..=lo)
+            .next_back()
+            .map_or(lo, |(&start, _)| start);
+        let overlapping: Vec<(T, T)> = self
+            .ranges
+            .range(scan_start..=hi)
+            .filter(|&(_, &end)| end >= lo)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+
+        for (start, end) in overlapping {
+            self.ranges.remove(&start);
+            if start < lo {
+                self.ranges.insert(start, lo.saturating_sub(T::one()));
+            }
+            if end > hi {
+                self.ranges.insert(hi.saturating_add(T::one()), end);
             }
         }
     }
 
-    pub fn to_vec(&self) -> Vec<RangeInclusive<T>> {
-        let mut vec = Vec::<RangeInclusive<T>>::new();
-        self.to_vec_req(&mut vec);
-        vec
+    /// Builds a `RangeTree` directly from a vector of already-sorted,
+    /// disjoint ranges, skipping the coalescing that `insert_range` does.
+    fn from_sorted_vec(ranges: Vec<RangeInclusive<T>>) -> Self {
+        RangeTree {
+            ranges: ranges.into_iter().map(|r| (*r.start(), *r.end())).collect(),
+        }
     }
 
-    fn to_vec_req(&self, vec: &mut Vec<RangeInclusive<T>>) {
-        if let Some(less) = &self.less {
-            less.to_vec_req(vec);
+    /// Combines `self` with `other`, merging overlapping and adjacent ranges
+    /// from both sets.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut tree = self.clone();
+        for r in other.to_vec() {
+            tree.insert_range(r);
         }
-        if let Some(last) = vec.last_mut() {
-            if last.end().saturating_add(T::one()) == *self.range.start() {
-                *last = *last.start()..=*self.range.end();
+        tree
+    }
+
+    /// Keeps only the portions of ranges that are present in both `self` and
+    /// `other`, found by walking both sorted range lists in lockstep.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let a = self.to_vec();
+        let b = other.to_vec();
+        let mut overlaps = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            let (ra, rb) = (&a[i], &b[j]);
+            let start = *ra.start().max(rb.start());
+            let end = *ra.end().min(rb.end());
+            if start <= end {
+                overlaps.push(start..=end);
+            }
+            if ra.end() <= rb.end() {
+                i += 1;
             } else {
-                vec.push(self.range.clone());
+                j += 1;
             }
-        } else {
-            vec.push(self.range.clone());
         }
+        RangeTree::from_sorted_vec(overlaps)
+    }
 
-        if let Some(more) = &self.more {
-            more.to_vec_req(vec);
+    /// Subtracts every range in `other` from `self`.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut tree = self.clone();
+        for r in other.to_vec() {
+            tree.remove_range(r);
         }
+        tree
     }
 
-    pub fn missed_in_range(&self, range: RangeInclusive<T>) -> Vec<RangeInclusive<T>> {
-        let filled = self.to_vec();
-        let head = filled.first().and_then(|c| {
-            (c.start() > range.start())
-                .then_some(*range.start()..=c.start().saturating_sub(T::one()))
-        });
-        let tail = filled.last().and_then(|c| {
-            (c.end() < range.end()).then_some(c.end().saturating_add(T::one())..=*range.end())
-        });
-        let missed = head
-            .into_iter()
-            .chain(filled.windows(2).map(|w| {
-                w[0].end().saturating_add(T::one()).max(*range.start())
-                    ..=w[1].start().saturating_sub(T::one()).min(*range.end())
-            }))
-            .chain(tail)
-            .filter(|m| m.end() >= range.start() && m.start() <= range.end())
-            .collect::<Vec<RangeInclusive<T>>>();
+    /// Checks whether `value` falls inside any stored range, in `O(log N)`.
+    pub fn contains(&self, value: T) -> bool {
+        self.ranges
+            .range(..=value)
+            .next_back()
+            .is_some_and(|(_, &end)| end >= value)
+    }
 
-        missed
+    /// Checks whether `r` lies entirely within a single stored range.
+    pub fn contains_range(&self, r: &RangeInclusive<T>) -> bool {
+        if r.is_empty() {
+            return true;
+        }
+        self.ranges
+            .range(..=*r.start())
+            .next_back()
+            .is_some_and(|(_, &end)| end >= *r.end())
+    }
+
+    /// Returns every stored range that overlaps `r`, without materializing
+    /// `to_vec()`.
+    pub fn overlapping(&self, r: &RangeInclusive<T>) -> Vec<RangeInclusive<T>> {
+        if r.is_empty() {
+            return Vec::new();
+        }
+        // Same bounded-scan trick as `insert_range`/`remove_range`: the
+        // predecessor of `r.start()` is the only stored range that can
+        // overlap from below.
+        let scan_start = self
+            .ranges
+            .range(..=*r.start())
+            .next_back()
+            .map_or(*r.start(), |(&start, _)| start);
+        self.ranges
+            .range(scan_start..=*r.end())
+            .filter(|&(_, &end)| end >= *r.start())
+            .map(|(&start, &end)| start..=end)
+            .collect()
+    }
+
+    /// Remaps every stored value through `rules`: each rule clips a source
+    /// span against a stored range and shifts the overlapping portion by its
+    /// offset, leaving unmatched sub-segments untouched. Mirrors the
+    /// seed-to-location transform from Advent of Code's "ranges" puzzles,
+    /// generalized to a `RangeTree`.
+    pub fn map_ranges(&self, rules: &[(RangeInclusive<T>, T)]) -> RangeTree<T> {
+        let mut mapped = RangeTree {
+            ranges: BTreeMap::new(),
+        };
+
+        for range in self.to_vec() {
+            let mut remainder = vec![range];
+            for (source, offset) in rules {
+                let mut unmatched = Vec::new();
+                for seg in remainder {
+                    let clip_start = *seg.start().max(source.start());
+                    let clip_end = *seg.end().min(source.end());
+                    if clip_start > clip_end {
+                        unmatched.push(seg);
+                        continue;
+                    }
+                    mapped.insert_range(
+                        clip_start.saturating_add(*offset)..=clip_end.saturating_add(*offset),
+                    );
+                    if seg.start() < &clip_start {
+                        unmatched.push(*seg.start()..=clip_start.saturating_sub(T::one()));
+                    }
+                    if seg.end() > &clip_end {
+                        unmatched.push(clip_end.saturating_add(T::one())..=*seg.end());
+                    }
+                }
+                remainder = unmatched;
+            }
+            for seg in remainder {
+                mapped.insert_range(seg);
+            }
+        }
+
+        mapped
     }
 }
 
@@ -141,7 +375,26 @@ where
     ranges
 }
 
+/// Takes a sequence of ranges and coalesces overlapping and adjacent ones into
+/// a sorted vector of merged ranges.
+/// # Example
+/// ```
+/// use range_rover::range_rover_ranges;
+/// let input = vec![0..=3, 10..=12, 4..=6, 13..=15];
+/// let result = range_rover_ranges(input);
+/// assert_eq!(result, vec![0..=6, 10..=15]);
+/// ```
+pub fn range_rover_ranges<I, T>(input: I) -> Vec<RangeInclusive<T>>
+where
+    I: IntoIterator<Item = RangeInclusive<T>>,
+    T: Copy + Clone + Integer + Saturating + One + Bounded,
+{
+    RangeTree::from_ranges(input).to_vec()
+}
+
 /// Takes a custom sequence of integers and range, produces a sorted vector of excluded ranges in range.
+/// `range` accepts any `RangeBounds`, so half-open and unbounded queries
+/// (`5..`, `..20`, `..`) work without knowing the type's min/max sentinels.
 /// # Example
 /// ```
 /// use range_rover::missed_in_range;
@@ -149,10 +402,18 @@ where
 /// let missed = missed_in_range(input, -10..=20);
 /// assert_eq!(missed, vec![-10..=-5, 5..=5, 11..=20]);
 /// ```
-pub fn missed_in_range<I, T>(input: I, range: RangeInclusive<T>) -> Vec<RangeInclusive<T>>
+///
+/// ```
+/// use range_rover::missed_in_range;
+/// let input = vec![0, 1, 2, 5, 6, 7];
+/// let missed = missed_in_range(input, 3..);
+/// assert_eq!(missed, vec![3..=4, 8..=i32::MAX]);
+/// ```
+pub fn missed_in_range<I, T, R>(input: I, range: R) -> Vec<RangeInclusive<T>>
 where
     I: IntoIterator<Item = T>,
     T: Copy + Clone + Integer + Saturating + One + Bounded,
+    R: RangeBounds<T>,
 {
     let mut missed = vec![];
     let mut input = input.into_iter();
@@ -213,4 +474,115 @@ mod tests {
         let missed = tree.missed_in_range(1..=10);
         assert_eq!(missed, vec![1..=2, 4..=6, 10..=10]);
     }
+
+    #[test]
+    fn insert_range() {
+        use super::RangeTree;
+        let mut tree = RangeTree::new(0);
+        tree.insert_range(2..=4);
+        tree.insert_range(8..=10);
+        tree.insert_range(5..=7);
+        assert_eq!(tree.to_vec(), vec![0..=0, 2..=10]);
+        tree.insert_range(-3..=-1);
+        assert_eq!(tree.to_vec(), vec![-3..=0, 2..=10]);
+        tree.insert_range(1..=1);
+        assert_eq!(tree.to_vec(), vec![-3..=10]);
+    }
+
+    #[test]
+    fn from_ranges() {
+        let input = vec![0..=3, 10..=12, 4..=6, 13..=15];
+        let result = range_rover_ranges(input);
+        assert_eq!(result, vec![0..=6, 10..=15]);
+    }
+
+    #[test]
+    fn remove() {
+        use super::RangeTree;
+        let mut tree = RangeTree::from_ranges(vec![0..=10]);
+        tree.remove(5);
+        assert_eq!(tree.to_vec(), vec![0..=4, 6..=10]);
+        tree.remove(0);
+        assert_eq!(tree.to_vec(), vec![1..=4, 6..=10]);
+        tree.remove(10);
+        assert_eq!(tree.to_vec(), vec![1..=4, 6..=9]);
+        tree.remove(100);
+        assert_eq!(tree.to_vec(), vec![1..=4, 6..=9]);
+    }
+
+    #[test]
+    fn remove_range() {
+        use super::RangeTree;
+        let mut tree = RangeTree::from_ranges(vec![0..=10, 20..=30]);
+        tree.remove_range(5..=25);
+        assert_eq!(tree.to_vec(), vec![0..=4, 26..=30]);
+        tree.remove_range(0..=30);
+        assert!(tree.to_vec().is_empty());
+    }
+
+    #[test]
+    fn set_algebra() {
+        use super::RangeTree;
+        let a = RangeTree::from_ranges(vec![0..=5, 10..=15]);
+        let b = RangeTree::from_ranges(vec![3..=12, 20..=25]);
+
+        assert_eq!(a.union(&b).to_vec(), vec![0..=15, 20..=25]);
+        assert_eq!(a.intersection(&b).to_vec(), vec![3..=5, 10..=12]);
+        assert_eq!(a.difference(&b).to_vec(), vec![0..=2, 13..=15]);
+    }
+
+    #[test]
+    fn queries() {
+        use super::RangeTree;
+        let tree = RangeTree::from_ranges(vec![0..=5, 10..=15, 20..=25]);
+
+        assert!(tree.contains(3));
+        assert!(!tree.contains(7));
+
+        assert!(tree.contains_range(&(11..=14)));
+        assert!(!tree.contains_range(&(4..=11)));
+
+        assert_eq!(tree.overlapping(&(4..=21)), vec![0..=5, 10..=15, 20..=25]);
+        assert_eq!(tree.overlapping(&(6..=9)), Vec::<std::ops::RangeInclusive<i32>>::new());
+    }
+
+    #[test]
+    fn map_ranges() {
+        use super::RangeTree;
+        let tree = RangeTree::from_ranges(vec![0..=9, 20..=24]);
+        let rules = [(5..=14, 100), (20..=22, -20)];
+        let mapped = tree.map_ranges(&rules);
+        assert_eq!(mapped.to_vec(), vec![0..=4, 23..=24, 105..=109]);
+    }
+
+    #[test]
+    fn map_ranges_saturates_on_overflow() {
+        use super::RangeTree;
+        let tree = RangeTree::from_ranges(vec![i32::MAX - 2..=i32::MAX]);
+        let rules = [(i32::MAX - 2..=i32::MAX, 10)];
+        let mapped = tree.map_ranges(&rules);
+        assert_eq!(mapped.to_vec(), vec![i32::MAX..=i32::MAX]);
+    }
+
+    #[test]
+    fn missed_in_range_bounds() {
+        use super::RangeTree;
+        let tree = RangeTree::from_ranges(vec![0..=2, 5..=7]);
+
+        assert_eq!(tree.missed_in_range(0..10), vec![3..=4, 8..=9]);
+        assert_eq!(tree.missed_in_range(3..=6), vec![3..=4]);
+        assert_eq!(tree.missed_in_range(8..), vec![8..=i32::MAX]);
+        assert_eq!(
+            tree.missed_in_range(..),
+            vec![i32::MIN..=-1, 3..=4, 8..=i32::MAX]
+        );
+    }
+
+    #[test]
+    fn missed_in_range_empty_tree() {
+        use super::RangeTree;
+        let mut tree = RangeTree::from_ranges(vec![0..=10]);
+        tree.remove_range(0..=10);
+        assert_eq!(tree.missed_in_range(-5..=15), vec![-5..=15]);
+    }
 }